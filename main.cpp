@@ -1,3 +1,5 @@
+#include <algorithm>
+#include <string>
 #include "wadutil64_def.h"
 
 typedef enum
@@ -29,8 +31,36 @@ typedef struct
     int         infotableofs;
 } wadinfo_t;
 
-static char input_file_name[128];
-static char output_file_name[128];
+typedef struct
+{
+    char        name[9];                /* without the compression bit */
+    const char* type;
+    byte        decode_mode;
+    bool        compressed;
+    int         filepos;
+    int         stored_size;
+    int         size;
+    unsigned int crc;
+} lumpentry_t;
+
+typedef enum
+{
+    PATCH_NONE,
+    PATCH_IPS,
+    PATCH_BPS
+} patchformat;
+
+typedef enum
+{
+    LIST_TEXT,
+    LIST_JSON,
+    LIST_HEADER,
+    LIST_CSV,
+    LIST_TSV
+} listformat;
+
+static std::string input_file_name;
+static std::string output_file_name;
 
 void choose_decode_mode(byte* decode_mode, char* lump_name)
 {
@@ -60,14 +90,28 @@ void choose_decode_mode(byte* decode_mode, char* lump_name)
     }
 }
 
+std::string output_name(const std::string& input_name, const char* suffix)
+{
+    // Replace the extension, DOOM64.z64 becomes DOOM64_extract.WAD
+    size_t length = input_name.size();
+    return input_name.substr(0, (length > 4) ? length - 4 : 0) + suffix;
+}
+
 void wadutil64_help()
 {
     printf("Improper arguments!\n");
     printf("USAGE:\n");
     printf("    Extraction: wadutil64.exe -e DOOM64_ROM.z64\n");
+    printf("    (ROM arguments may also be a flashcart SD directory containing the ROM)\n");
     printf("    Decompression: wadutil64.exe -d DOOM64.WAD\n");
     printf("    Compression: wadutil64.exe -c DOOM64.WAD\n");
     printf("    Padding: wadutil64.exe -p DOOM64.WAD\n");
+    printf("    Listing: wadutil64.exe -l DOOM64.WAD [text|json|header|csv|tsv]\n");
+    printf("    Diff: wadutil64.exe -f FIRST.WAD SECOND.WAD (either may be a ROM)\n");
+    printf("    Map stats: wadutil64.exe -m DOOM64.WAD MAP01\n");
+    printf("    ROM info: wadutil64.exe -i DOOM64_ROM.z64\n");
+    printf("    ROM header: wadutil64.exe -n DOOM64_ROM.z64 [name=NAME] [region=E] [revision=1]\n");
+    printf("    Injection: wadutil64.exe -j DOOM64_ROM.z64 DOOM64.WAD [z64|ips|bps]\n");
 }
 
 lumpinfo_t* read_lump_directory(FILE* WAD, int number_of_lumps, int offset)
@@ -105,9 +149,36 @@ byte* read_lump(FILE* WAD, int offset, int size)
     return lump_data;
 }
 
-void extract_WAD(FILE* input_ROM, FILE* output_WAD)
+bool extract_WAD(FILE* input_ROM, FILE* output_WAD)
 {
-    printf("TODO: WAD EXTRACTION!!!\n");
+    std::vector<byte> rom = ROM_Read(input_ROM, NULL);
+    if (rom.empty())
+    {
+        return false;
+    }
+
+    // Offsets differ between revisions and overdumps, so look for the data instead
+    int wad_size;
+    int wad_offset = ROM_FindIWAD(rom, &wad_size);
+    if (wad_offset < 0)
+    {
+        printf("ERROR: Could not find an IWAD in the ROM.\n");
+        return false;
+    }
+    printf("Found IWAD at %X, size %d\n", wad_offset, wad_size);
+
+    const char* sound_magics[] = { "SN64", "SSEQ" };
+    for (const char* magic : sound_magics)
+    {
+        int offset = ROM_FindSignature(rom, magic);
+        if (offset >= 0)
+        {
+            printf("Found %s at %X\n", magic, offset);
+        }
+    }
+
+    fwrite(&rom[wad_offset], wad_size, 1, output_WAD);
+    return true;
 }
 
 byte* decompress_lump_data(byte* lump_data, int new_size, byte decode_mode)
@@ -162,7 +233,7 @@ void decompress_WAD(FILE* input_WAD, FILE* output_WAD)
     // Read WAD header
     wadinfo_t wad_header;
     fread(&wad_header, sizeof(wadinfo_t), 1, input_WAD);
-    printf("WAD name: %s\n", input_file_name);
+    printf("WAD name: %s\n", input_file_name.c_str());
     printf("Number of lumps: %d, Address to lump directory: %X\n", wad_header.numlumps, wad_header.infotableofs);
 
     // Read list of all lumps
@@ -258,7 +329,7 @@ void compress_WAD(FILE* input_WAD, FILE* output_WAD)
     // Read WAD header
     wadinfo_t wad_header;
     fread(&wad_header, sizeof(wadinfo_t), 1, input_WAD);
-    printf("WAD name: %s\n", input_file_name);
+    printf("WAD name: %s\n", input_file_name.c_str());
     printf("Number of lumps: %d, Address to lump directory: %X\n", wad_header.numlumps, wad_header.infotableofs);
 
     // Read list of all lumps
@@ -326,7 +397,7 @@ void pad_WAD(FILE* input_WAD, FILE* output_WAD)
     // Read WAD header
     wadinfo_t wad_header;
     fread(&wad_header, sizeof(wadinfo_t), 1, input_WAD);
-    printf("WAD name: %s\n", input_file_name);
+    printf("WAD name: %s\n", input_file_name.c_str());
     printf("Number of lumps: %d, Address to lump directory: %X\n", wad_header.numlumps, wad_header.infotableofs);
 
     // Read list of all lumps
@@ -368,10 +439,1079 @@ void pad_WAD(FILE* input_WAD, FILE* output_WAD)
     free(lump_directory);
 }
 
+const char* decode_mode_name(byte decode_mode)
+{
+    switch (decode_mode)
+    {
+    case DECODE_JAGUAR:
+        return "jaguar";
+    case DECODE_D64:
+        return "d64";
+    default:
+        return "none";
+    }
+}
+
+void choose_lump_section(const char** lump_section, char* lump_name)
+{
+    if (!strcmp(lump_name, "S_START"))
+    {
+        *lump_section = "sprite";
+    }
+    else if (!strcmp(lump_name, "S_END"))
+    {
+        *lump_section = "other";
+    }
+    else if (!strcmp(lump_name, "T_START"))
+    {
+        *lump_section = "texture";
+    }
+    else if (!strcmp(lump_name, "T_END"))
+    {
+        *lump_section = "graphic";
+    }
+}
+
+const char* lump_type_name(const char* lump_section, char* lump_name, int size)
+{
+    if (size <= 0)
+    {
+        return "marker";
+    }
+    if (!strncmp(lump_name, "MAP", 3))
+    {
+        return "map";
+    }
+    if (!strncmp(lump_name, "DEMO", 4))
+    {
+        return "demo";
+    }
+
+    return lump_section;
+}
+
+unsigned int crc32(const byte* data, int size)
+{
+    unsigned int crc = 0xFFFFFFFF;
+
+    for (int i = 0; i < size; ++i)
+    {
+        crc ^= data[i];
+        for (int bit = 0; bit < 8; ++bit)
+        {
+            crc = (crc >> 1) ^ (0xEDB88320 & (0 - (crc & 1)));
+        }
+    }
+
+    return ~crc;
+}
+
+bool read_wad_header(FILE* WAD, wadinfo_t* wad_header)
+{
+    fseek(WAD, 0, SEEK_END);
+    long long file_size = ftell(WAD);
+    fseek(WAD, 0, SEEK_SET);
+
+    // Reject anything whose lump directory would not fit, before allocating for it
+    if (fread(wad_header, sizeof(wadinfo_t), 1, WAD) != 1
+        || (memcmp(wad_header->identification, "IWAD", 4) && memcmp(wad_header->identification, "PWAD", 4))
+        || wad_header->numlumps <= 0
+        || wad_header->infotableofs < (int) sizeof(wadinfo_t)
+        || (long long) wad_header->infotableofs + (long long) wad_header->numlumps * (long long) sizeof(lumpinfo_t) > file_size)
+    {
+        return false;
+    }
+
+    return true;
+}
+
+lumpentry_t* read_lump_entries(FILE* WAD, wadinfo_t* wad_header)
+{
+    // Read list of all lumps
+    lumpinfo_t* lump_directory = read_lump_directory(WAD, wad_header->numlumps, wad_header->infotableofs);
+
+    lumpentry_t* lump_entries = (lumpentry_t*) malloc(wad_header->numlumps * sizeof(lumpentry_t));
+    if (!lump_entries)
+    {
+        printf("ERROR: Could not read WAD lumps.");
+        exit(EXIT_FAILURE);
+    }
+
+    fseek(WAD, 0, SEEK_END);
+    long long file_size = ftell(WAD);
+
+    byte decode_mode = DECODE_NONE;
+    const char* lump_section = "other";
+
+    for (int i = 0; i < wad_header->numlumps; ++i)
+    {
+        lumpinfo_t* lump_info = &(lump_directory[i]);
+        lumpentry_t* lump_entry = &(lump_entries[i]);
+        bool compressed = (lump_info->name[0] & 0x80) != 0;
+
+        strncpy(lump_entry->name, lump_info->name, 8);
+        lump_entry->name[8] = 0;
+        lump_entry->name[0] &= 0x7F;
+
+        // Compressed lumps don't record their stored size, so it is the distance to
+        // whatever comes next in the file, regardless of directory order
+        long long stored_size = lump_info->size;
+        if (compressed)
+        {
+            long long next_filepos = file_size;
+            if (wad_header->infotableofs > lump_info->filepos)
+            {
+                next_filepos = std::min(next_filepos, (long long) wad_header->infotableofs);
+            }
+            for (int j = 0; j < wad_header->numlumps; ++j)
+            {
+                if (lump_directory[j].size > 0 && lump_directory[j].filepos > lump_info->filepos)
+                {
+                    next_filepos = std::min(next_filepos, (long long) lump_directory[j].filepos);
+                }
+            }
+            stored_size = next_filepos - lump_info->filepos;
+        }
+
+        if (lump_info->size <= 0)
+        {
+            stored_size = 0;
+        }
+        else if (stored_size < 0 || lump_info->filepos < 0 || lump_info->filepos + stored_size > file_size)
+        {
+            // stderr keeps machine readable listings on stdout intact
+            fprintf(stderr, "WARNING: Lump %d (%s) at %X does not fit in the WAD, ignoring its data\n",
+                i, lump_entry->name, lump_info->filepos);
+            stored_size = 0;
+        }
+
+        choose_decode_mode(&decode_mode, lump_info->name);
+        choose_lump_section(&lump_section, lump_entry->name);
+
+        lump_entry->type = lump_type_name(lump_section, lump_entry->name, lump_info->size);
+        lump_entry->decode_mode = decode_mode;
+        lump_entry->compressed = compressed;
+        lump_entry->filepos = lump_info->filepos;
+        lump_entry->stored_size = (int) stored_size;
+        lump_entry->size = lump_info->size;
+        lump_entry->crc = 0;
+
+        if (stored_size > 0)
+        {
+            byte* lump_data = read_lump(WAD, lump_info->filepos, lump_entry->stored_size);
+            lump_entry->crc = crc32(lump_data, lump_entry->stored_size);
+            free(lump_data);
+        }
+    }
+
+    free(lump_directory);
+    return lump_entries;
+}
+
+void lump_identifier(const char* lump_name, char* identifier)
+{
+    int i = 0;
+    for (; lump_name[i] && i < 8; ++i)
+    {
+        identifier[i] = isalnum((unsigned char) lump_name[i]) ? toupper((unsigned char) lump_name[i]) : '_';
+    }
+    identifier[i] = 0;
+}
+
+bool list_WAD(FILE* input_WAD, byte list_format)
+{
+    // Read WAD header
+    wadinfo_t wad_header;
+    if (!read_wad_header(input_WAD, &wad_header))
+    {
+        printf("ERROR: %s is not a valid WAD.\n", input_file_name.c_str());
+        return false;
+    }
+
+    lumpentry_t* lump_entries = read_lump_entries(input_WAD, &wad_header);
+
+    if (list_format == LIST_JSON)
+    {
+        printf("{\n");
+        printf("  \"identification\": \"%.4s\",\n", wad_header.identification);
+        printf("  \"numlumps\": %d,\n", wad_header.numlumps);
+        printf("  \"infotableofs\": %d,\n", wad_header.infotableofs);
+        printf("  \"lumps\": [");
+    }
+    else if (list_format == LIST_HEADER)
+    {
+        printf("/* Generated by wadutil64 from %s */\n\n", input_file_name.c_str());
+        printf("#ifndef DOOM64_WAD_H\n");
+        printf("#define DOOM64_WAD_H\n\n");
+        printf("#define NUMLUMPS %d\n\n", wad_header.numlumps);
+    }
+    else if (list_format == LIST_CSV || list_format == LIST_TSV)
+    {
+        const char* separator = (list_format == LIST_CSV) ? "," : "\t";
+        printf("index%sname%stype%scompression%sstored_size%ssize%soffset%scrc32\n",
+            separator, separator, separator, separator, separator, separator, separator);
+    }
+    else
+    {
+        printf("WAD name: %s\n", input_file_name.c_str());
+        printf("Number of lumps: %d, Address to lump directory: %X\n", wad_header.numlumps, wad_header.infotableofs);
+        printf("%5s  %-8s  %-7s  %-11s  %8s  %8s  %8s  %8s\n",
+            "INDEX", "NAME", "TYPE", "COMPRESSION", "OFFSET", "STORED", "SIZE", "CRC32");
+    }
+
+    for (int i = 0; i < wad_header.numlumps; ++i)
+    {
+        lumpentry_t* lump_entry = &(lump_entries[i]);
+        const char* compression = lump_entry->compressed ? decode_mode_name(lump_entry->decode_mode) : "none";
+
+        if (list_format == LIST_JSON)
+        {
+            printf("%s\n    {\"index\": %d, \"name\": \"", (i > 0) ? "," : "", i);
+            // Control and non-ASCII bytes are escaped as Latin-1 so the output is always valid JSON
+            for (char* c = lump_entry->name; *c; ++c)
+            {
+                unsigned char ch = (unsigned char) *c;
+                if (ch < 0x20 || ch >= 0x7F)
+                {
+                    printf("\\u%04X", ch);
+                    continue;
+                }
+                if (ch == '"' || ch == '\\')
+                {
+                    putchar('\\');
+                }
+                putchar(ch);
+            }
+            printf("\", \"type\": \"%s\", \"compression\": \"%s\", \"offset\": %d, \"stored_size\": %d, \"size\": %d, \"crc32\": \"%08X\"}",
+                lump_entry->type, compression, lump_entry->filepos, lump_entry->stored_size, lump_entry->size, lump_entry->crc);
+        }
+        else if (list_format == LIST_HEADER)
+        {
+            // Only the first lump of a repeated identifier gets a constant, like W_GetNumForName
+            // would find. Names such as A-B and A_B map to the same identifier too.
+            char identifier[9];
+            lump_identifier(lump_entry->name, identifier);
+
+            int previous = -1;
+            for (int j = 0; j < i && previous < 0; ++j)
+            {
+                char previous_identifier[9];
+                lump_identifier(lump_entries[j].name, previous_identifier);
+                if (!strcmp(previous_identifier, identifier))
+                {
+                    previous = j;
+                }
+            }
+            if (previous >= 0)
+            {
+                if (strcmp(lump_entries[previous].name, lump_entry->name))
+                {
+                    printf("/* lump %d skipped, LUMP_%s is already lump %d */\n", i, identifier, previous);
+                }
+                continue;
+            }
+
+            printf("#define LUMP_%s", identifier);
+            printf(" %d\n", i);
+        }
+        else if (list_format == LIST_CSV || list_format == LIST_TSV)
+        {
+            const char* separator = (list_format == LIST_CSV) ? "," : "\t";
+            printf("%d%s", i, separator);
+
+            // Quote names that would break a CSV row
+            if (list_format == LIST_CSV && strpbrk(lump_entry->name, ",\"\r\n"))
+            {
+                putchar('"');
+                for (char* c = lump_entry->name; *c; ++c)
+                {
+                    if (*c == '"')
+                    {
+                        putchar('"');
+                    }
+                    putchar(*c);
+                }
+                putchar('"');
+            }
+            else if (list_format == LIST_TSV && strpbrk(lump_entry->name, "\t\r\n\\"))
+            {
+                // TSV has no quoting, so escape the characters that would split a field or row
+                for (char* c = lump_entry->name; *c; ++c)
+                {
+                    switch (*c)
+                    {
+                    case '\t': printf("\\t"); break;
+                    case '\r': printf("\\r"); break;
+                    case '\n': printf("\\n"); break;
+                    case '\\': printf("\\\\"); break;
+                    default: putchar(*c); break;
+                    }
+                }
+            }
+            else
+            {
+                printf("%s", lump_entry->name);
+            }
+
+            printf("%s%s%s%s%s%d%s%d%s%d%s%08X\n",
+                separator, lump_entry->type, separator, compression, separator, lump_entry->stored_size,
+                separator, lump_entry->size, separator, lump_entry->filepos, separator, lump_entry->crc);
+        }
+        else
+        {
+            printf("%5d  %-8s  %-7s  %-11s  %8X  %8d  %8d  %08X\n",
+                i, lump_entry->name, lump_entry->type, compression, lump_entry->filepos, lump_entry->stored_size, lump_entry->size, lump_entry->crc);
+        }
+    }
+
+    if (list_format == LIST_JSON)
+    {
+        printf("\n  ]\n}\n");
+    }
+    else if (list_format == LIST_HEADER)
+    {
+        printf("\n#endif\n");
+    }
+
+    free(lump_entries);
+    return true;
+}
+
+bool lump_is_readable(lumpentry_t* lump_entry)
+{
+    if (lump_entry->size <= 0)
+    {
+        return true;
+    }
+
+    // Uncompressed lumps are read as a whole, compressed ones need at least some data
+    return lump_entry->compressed ? lump_entry->stored_size > 0 : lump_entry->stored_size >= lump_entry->size;
+}
+
+unsigned int decompressed_lump_crc(FILE* WAD, lumpentry_t* lump_entry)
+{
+    if (!lump_entry->compressed)
+    {
+        byte* lump_data = read_lump(WAD, lump_entry->filepos, lump_entry->size);
+        unsigned int lump_crc = crc32(lump_data, lump_entry->size);
+        free(lump_data);
+        return lump_crc;
+    }
+
+    byte* lump_data = read_lump(WAD, lump_entry->filepos, lump_entry->stored_size);
+    lump_data = decompress_lump_data(lump_data, lump_entry->size, lump_entry->decode_mode);
+
+    unsigned int lump_crc = crc32(lump_data, lump_entry->size);
+    free(lump_data);
+
+    return lump_crc;
+}
+
+FILE* open_WAD_or_ROM(std::string& file_name)
+{
+    if (!ROM_ResolvePath(file_name))
+    {
+        return NULL;
+    }
+    FILE* file = fopen(file_name.c_str(), "rb");
+    if (!file)
+    {
+        printf("ERROR: Input file %s not found!\n", file_name.c_str());
+        return NULL;
+    }
+    if (ROM_ReadFormat(file) < 0)
+    {
+        return file;
+    }
+
+    // ROMs are compared by the IWAD inside them, copied to a temporary file
+    std::vector<byte> rom = ROM_Read(file, NULL);
+    fclose(file);
+    if (rom.empty())
+    {
+        return NULL;
+    }
+
+    int wad_size;
+    int wad_offset = ROM_FindIWAD(rom, &wad_size);
+    if (wad_offset < 0)
+    {
+        printf("ERROR: Could not find an IWAD in %s.\n", file_name.c_str());
+        return NULL;
+    }
+
+    FILE* wad_file = tmpfile();
+    if (!wad_file)
+    {
+        printf("ERROR: Could not create a temporary file for the IWAD of %s.\n", file_name.c_str());
+        return NULL;
+    }
+    fwrite(&rom[wad_offset], wad_size, 1, wad_file);
+    fseek(wad_file, 0, SEEK_SET);
+
+    return wad_file;
+}
+
+bool diff_WAD(FILE* first_WAD, FILE* second_WAD, const char* first_name, const char* second_name)
+{
+    wadinfo_t first_header;
+    if (!read_wad_header(first_WAD, &first_header))
+    {
+        printf("ERROR: %s is not a valid WAD.\n", first_name);
+        return false;
+    }
+    wadinfo_t second_header;
+    if (!read_wad_header(second_WAD, &second_header))
+    {
+        printf("ERROR: %s is not a valid WAD.\n", second_name);
+        return false;
+    }
+
+    printf("Comparing %s (%d lumps) with %s (%d lumps)\n", first_name, first_header.numlumps, second_name, second_header.numlumps);
+
+    lumpentry_t* first_entries = read_lump_entries(first_WAD, &first_header);
+    lumpentry_t* second_entries = read_lump_entries(second_WAD, &second_header);
+
+    // Remember which lumps of the second WAD were paired up, names like '?' repeat
+    bool* second_matched = (bool*) calloc(second_header.numlumps > 0 ? second_header.numlumps : 1, sizeof(bool));
+    if (!second_matched)
+    {
+        printf("ERROR: Could not compare WAD lumps.");
+        exit(EXIT_FAILURE);
+    }
+
+    int added = 0, removed = 0, changed = 0, unchanged = 0;
+    int search_start = 0;
+
+    for (int i = 0; i < first_header.numlumps; ++i)
+    {
+        lumpentry_t* first_entry = &(first_entries[i]);
+
+        // Pair with the next unmatched lump of the same name, keeping the order of duplicates
+        int match = -1;
+        for (int j = search_start; j < second_header.numlumps; ++j)
+        {
+            if (!second_matched[j] && !strcmp(first_entry->name, second_entries[j].name))
+            {
+                match = j;
+                break;
+            }
+        }
+
+        if (match < 0)
+        {
+            printf("Removed: %-8s (%s, %d bytes)\n", first_entry->name, first_entry->type, first_entry->size);
+            ++removed;
+            continue;
+        }
+
+        second_matched[match] = true;
+        lumpentry_t* second_entry = &(second_entries[match]);
+        if (match == search_start)
+        {
+            ++search_start;
+        }
+
+        if (!lump_is_readable(first_entry) || !lump_is_readable(second_entry))
+        {
+            printf("Unreadable: %-8s (%s, data missing from %s)\n", first_entry->name, first_entry->type,
+                lump_is_readable(first_entry) ? second_name : first_name);
+            ++changed;
+            continue;
+        }
+
+        bool same = (first_entry->size == second_entry->size);
+        if (same && (first_entry->compressed != second_entry->compressed || first_entry->crc != second_entry->crc))
+        {
+            // Stored bytes differ, compare the decompressed contents instead
+            same = first_entry->size <= 0
+                || decompressed_lump_crc(first_WAD, first_entry) == decompressed_lump_crc(second_WAD, second_entry);
+        }
+
+        if (same)
+        {
+            ++unchanged;
+        }
+        else
+        {
+            printf("Changed: %-8s (%s, %d -> %d bytes)\n", first_entry->name, first_entry->type, first_entry->size, second_entry->size);
+            ++changed;
+        }
+    }
+
+    for (int j = 0; j < second_header.numlumps; ++j)
+    {
+        if (!second_matched[j])
+        {
+            printf("Added:   %-8s (%s, %d bytes)\n", second_entries[j].name, second_entries[j].type, second_entries[j].size);
+            ++added;
+        }
+    }
+
+    printf("%d added, %d removed, %d changed, %d unchanged\n", added, removed, changed, unchanged);
+
+    free(second_matched);
+    free(first_entries);
+    free(second_entries);
+
+    return added == 0 && removed == 0 && changed == 0;
+}
+
+short read_map_short(byte* data, int offset)
+{
+    return (short) (data[offset] | (data[offset + 1] << 8));
+}
+
+void count_map_indices(std::vector<int>& counts, int index)
+{
+    if (index < 0)
+    {
+        return;
+    }
+    if (index >= (int) counts.size())
+    {
+        counts.resize(index + 1, 0);
+    }
+    counts[index]++;
+}
+
+void print_map_indices(const char* label, std::vector<int>& counts)
+{
+    int used = 0;
+    for (int count : counts)
+    {
+        used += (count > 0);
+    }
+    printf("%s used: %d\n", label, used);
+    for (int i = 0; i < (int) counts.size(); ++i)
+    {
+        if (counts[i] > 0)
+        {
+            printf("    %5d: %d\n", i, counts[i]);
+        }
+    }
+}
+
+bool inspect_map(FILE* input_WAD, const char* map_name)
+{
+    // Read WAD header
+    wadinfo_t wad_header;
+    if (!read_wad_header(input_WAD, &wad_header))
+    {
+        printf("ERROR: %s is not a valid WAD.\n", input_file_name.c_str());
+        return false;
+    }
+
+    lumpentry_t* lump_entries = read_lump_entries(input_WAD, &wad_header);
+
+    lumpentry_t* map_entry = NULL;
+    for (int i = 0; i < wad_header.numlumps && !map_entry; ++i)
+    {
+        if (!strcmp(lump_entries[i].name, map_name) && lump_entries[i].size > 0)
+        {
+            map_entry = &(lump_entries[i]);
+        }
+    }
+    if (!map_entry)
+    {
+        printf("ERROR: Map %s not found in %s!\n", map_name, input_file_name.c_str());
+        free(lump_entries);
+        return false;
+    }
+    if (!lump_is_readable(map_entry) || map_entry->size < (int) sizeof(wadinfo_t))
+    {
+        printf("ERROR: %s does not contain a map WAD!\n", map_name);
+        free(lump_entries);
+        return false;
+    }
+
+    // Maps are stored as a whole WAD inside the lump
+    byte* map_data;
+    if (map_entry->compressed)
+    {
+        map_data = read_lump(input_WAD, map_entry->filepos, map_entry->stored_size);
+        map_data = decompress_lump_data(map_data, map_entry->size, map_entry->decode_mode);
+    }
+    else
+    {
+        map_data = read_lump(input_WAD, map_entry->filepos, map_entry->size);
+    }
+    int map_size = map_entry->size;
+    free(lump_entries);
+
+    wadinfo_t map_header;
+    memcpy(&map_header, map_data, sizeof(wadinfo_t));
+    if (map_header.numlumps <= 0
+        || map_header.infotableofs < (int) sizeof(wadinfo_t)
+        || (long long) map_header.infotableofs + (long long) map_header.numlumps * (long long) sizeof(lumpinfo_t) > map_size)
+    {
+        printf("ERROR: %s does not contain a map WAD!\n", map_name);
+        free(map_data);
+        return false;
+    }
+
+    printf("Map: %s, %d lumps, %d bytes\n", map_name, map_header.numlumps, map_size);
+
+    std::vector<int> thing_types;
+    std::vector<int> textures;
+    std::vector<int> flats;
+
+    for (int i = 0; i < map_header.numlumps; ++i)
+    {
+        lumpinfo_t lump_info;
+        memcpy(&lump_info, map_data + map_header.infotableofs + i * sizeof(lumpinfo_t), sizeof(lumpinfo_t));
+
+        char lump_name[9];
+        strncpy(lump_name, lump_info.name, 8);
+        lump_name[8] = 0;
+        lump_name[0] &= 0x7F;
+
+        if (lump_info.filepos < 0 || lump_info.size < 0 || (long long) lump_info.filepos + (long long) lump_info.size > map_size)
+        {
+            printf("    %-8s  out of bounds\n", lump_name);
+            continue;
+        }
+        if (lump_info.name[0] & 0x80)
+        {
+            printf("    %-8s  %8d bytes (compressed)\n", lump_name, lump_info.size);
+            continue;
+        }
+
+        byte* lump_data = map_data + lump_info.filepos;
+        int record_size = 0;
+
+        if (!strcmp(lump_name, "THINGS"))
+        {
+            record_size = 14;
+            for (int j = 0; j + record_size <= lump_info.size; j += record_size)
+            {
+                count_map_indices(thing_types, read_map_short(lump_data, j + 8));
+            }
+        }
+        else if (!strcmp(lump_name, "LINEDEFS"))
+        {
+            record_size = 16;
+        }
+        else if (!strcmp(lump_name, "SIDEDEFS"))
+        {
+            record_size = 12;
+            for (int j = 0; j + record_size <= lump_info.size; j += record_size)
+            {
+                count_map_indices(textures, read_map_short(lump_data, j + 4));
+                count_map_indices(textures, read_map_short(lump_data, j + 6));
+                count_map_indices(textures, read_map_short(lump_data, j + 8));
+            }
+        }
+        else if (!strcmp(lump_name, "VERTEXES"))
+        {
+            record_size = 8;
+        }
+        else if (!strcmp(lump_name, "SECTORS"))
+        {
+            record_size = 24;
+            for (int j = 0; j + record_size <= lump_info.size; j += record_size)
+            {
+                count_map_indices(flats, read_map_short(lump_data, j + 4));
+                count_map_indices(flats, read_map_short(lump_data, j + 6));
+            }
+        }
+        else if (!strcmp(lump_name, "LIGHTS"))
+        {
+            record_size = 6;
+        }
+
+        if (record_size > 0)
+        {
+            printf("    %-8s  %8d bytes, %d entries\n", lump_name, lump_info.size, lump_info.size / record_size);
+        }
+        else if (!strcmp(lump_name, "MACROS") && lump_info.size >= 2)
+        {
+            printf("    %-8s  %8d bytes, %d macros\n", lump_name, lump_info.size, read_map_short(lump_data, 0));
+        }
+        else
+        {
+            printf("    %-8s  %8d bytes\n", lump_name, lump_info.size);
+        }
+    }
+
+    print_map_indices("Thing types", thing_types);
+    print_map_indices("Wall textures", textures);
+    print_map_indices("Flats", flats);
+
+    free(map_data);
+    return true;
+}
+
+bool inject_WAD(FILE* input_ROM, FILE* input_WAD, FILE* output_file, byte patch_format)
+{
+    byte rom_format;
+    std::vector<byte> rom = ROM_Read(input_ROM, &rom_format);
+    if (rom.empty())
+    {
+        return false;
+    }
+
+    std::vector<byte> original_rom;
+    if (patch_format != PATCH_NONE)
+    {
+        original_rom = rom;
+    }
+
+    int old_wad_size;
+    int wad_offset = ROM_FindIWAD(rom, &old_wad_size);
+    if (wad_offset < 0)
+    {
+        printf("ERROR: Could not find an IWAD in the ROM.\n");
+        return false;
+    }
+    printf("Found IWAD at %X, size %d\n", wad_offset, old_wad_size);
+
+    fseek(input_WAD, 0, SEEK_END);
+    long wad_file_size = ftell(input_WAD);
+    if (wad_file_size < (long) sizeof(wadinfo_t))
+    {
+        printf("ERROR: Input WAD is empty or could not be read.\n");
+        return false;
+    }
+
+    // Make sure this really is an IWAD before overwriting the one in the ROM
+    wadinfo_t wad_header;
+    if (!read_wad_header(input_WAD, &wad_header) || memcmp(wad_header.identification, "IWAD", 4))
+    {
+        printf("ERROR: Input file is not a valid IWAD.\n");
+        return false;
+    }
+
+    // A smaller IWAD injected earlier leaves zeros behind it, so the free space runs
+    // up to the sound data that follows or to the end of the padding, whichever is first
+    int rom_size = (int) rom.size();
+    int wad_capacity_end = wad_offset + old_wad_size;
+    while (wad_capacity_end < rom_size && rom[wad_capacity_end] == 0)
+    {
+        ++wad_capacity_end;
+    }
+
+    const char* sound_magics[] = { "SN64", "SSEQ" };
+    for (const char* magic : sound_magics)
+    {
+        int offset = ROM_FindSignature(rom, magic);
+        if (offset >= wad_offset + old_wad_size && offset < wad_capacity_end)
+        {
+            wad_capacity_end = offset;
+        }
+    }
+
+    long wad_capacity = wad_capacity_end - wad_offset;
+    if (wad_file_size > wad_capacity)
+    {
+        printf("ERROR: WAD is %ld bytes larger than the space for it in the ROM.\n", wad_file_size - wad_capacity);
+        return false;
+    }
+
+    int new_wad_size = (int) wad_file_size;
+    byte* wad_data = read_lump(input_WAD, 0, new_wad_size);
+
+    memcpy(&rom[wad_offset], wad_data, new_wad_size);
+    if (new_wad_size < old_wad_size)
+    {
+        memset(&rom[wad_offset + new_wad_size], 0, old_wad_size - new_wad_size);
+    }
+    free(wad_data);
+
+    ROM_FixCRC(rom);
+
+    if (patch_format == PATCH_NONE)
+    {
+        fwrite(rom.data(), rom.size(), 1, output_file);
+        return true;
+    }
+
+    // Patches have to apply to the file the user has, not to its .z64 form
+    if (rom_format != ROM_FORMAT_Z64)
+    {
+        printf("Creating patch against the original %s byte order\n", (rom_format == ROM_FORMAT_V64) ? ".v64" : ".n64");
+        ROM_SwapFormat(original_rom, rom_format);
+        ROM_SwapFormat(rom, rom_format);
+    }
+
+    std::vector<byte> patch = (patch_format == PATCH_IPS)
+        ? Patch_CreateIPS(original_rom, rom)
+        : Patch_CreateBPS(original_rom, rom);
+    if (patch.empty())
+    {
+        return false;
+    }
+
+    printf("Patch size: %d bytes\n", (int) patch.size());
+    fwrite(patch.data(), patch.size(), 1, output_file);
+    return true;
+}
+
 int main(int argc, char** argv)
 {
     std::ios::sync_with_stdio(false);
 
+    if (argc < 3)
+    {
+        wadutil64_help();
+        return EXIT_FAILURE;
+    }
+
+    // Listing only reads the WAD and prints to stdout
+    if (argv[1][1] == 'l')
+    {
+        byte list_format;
+        if (argc == 3 || !strcmp(argv[3], "text"))
+        {
+            list_format = LIST_TEXT;
+        }
+        else if (argc == 4 && !strcmp(argv[3], "json"))
+        {
+            list_format = LIST_JSON;
+        }
+        else if (argc == 4 && !strcmp(argv[3], "header"))
+        {
+            list_format = LIST_HEADER;
+        }
+        else if (argc == 4 && !strcmp(argv[3], "csv"))
+        {
+            list_format = LIST_CSV;
+        }
+        else if (argc == 4 && !strcmp(argv[3], "tsv"))
+        {
+            list_format = LIST_TSV;
+        }
+        else
+        {
+            wadutil64_help();
+            return EXIT_FAILURE;
+        }
+
+        input_file_name = argv[2];
+        FILE* input_file = fopen(input_file_name.c_str(), "rb");
+        if (!input_file)
+        {
+            printf("ERROR: Input file %s not found!\n", input_file_name.c_str());
+            return EXIT_FAILURE;
+        }
+
+        bool listed = list_WAD(input_file, list_format);
+
+        fclose(input_file);
+        return listed ? EXIT_SUCCESS : EXIT_FAILURE;
+    }
+
+    // Diffing reads two WADs and only reports the differences
+    if (argv[1][1] == 'f')
+    {
+        if (argc != 4)
+        {
+            wadutil64_help();
+            return EXIT_FAILURE;
+        }
+
+        std::string first_name = argv[2];
+        FILE* first_file = open_WAD_or_ROM(first_name);
+        if (!first_file)
+        {
+            return EXIT_FAILURE;
+        }
+        std::string second_name = argv[3];
+        FILE* second_file = open_WAD_or_ROM(second_name);
+        if (!second_file)
+        {
+            fclose(first_file);
+            return EXIT_FAILURE;
+        }
+
+        bool identical = diff_WAD(first_file, second_file, first_name.c_str(), second_name.c_str());
+
+        fclose(first_file);
+        fclose(second_file);
+        return identical ? EXIT_SUCCESS : EXIT_FAILURE;
+    }
+
+    // Map inspection reads one map out of the WAD and prints its stats
+    if (argv[1][1] == 'm')
+    {
+        if (argc != 4)
+        {
+            wadutil64_help();
+            return EXIT_FAILURE;
+        }
+
+        input_file_name = argv[2];
+        FILE* input_file = fopen(input_file_name.c_str(), "rb");
+        if (!input_file)
+        {
+            printf("ERROR: Input file %s not found!\n", input_file_name.c_str());
+            return EXIT_FAILURE;
+        }
+
+        bool found = inspect_map(input_file, argv[3]);
+
+        fclose(input_file);
+        return found ? EXIT_SUCCESS : EXIT_FAILURE;
+    }
+
+    // ROM info only reads the ROM header and checks its CRC
+    if (argv[1][1] == 'i')
+    {
+        if (argc != 3)
+        {
+            wadutil64_help();
+            return EXIT_FAILURE;
+        }
+
+        input_file_name = argv[2];
+        if (!ROM_ResolvePath(input_file_name))
+        {
+            return EXIT_FAILURE;
+        }
+        FILE* input_ROM = fopen(input_file_name.c_str(), "rb");
+        if (!input_ROM)
+        {
+            printf("ERROR: Input file %s not found!\n", input_file_name.c_str());
+            return EXIT_FAILURE;
+        }
+
+        std::vector<byte> rom = ROM_Read(input_ROM, NULL);
+        fclose(input_ROM);
+        if (rom.empty())
+        {
+            return EXIT_FAILURE;
+        }
+
+        printf("ROM name: %s\n", input_file_name.c_str());
+        return ROM_PrintHeader(rom) ? EXIT_SUCCESS : EXIT_FAILURE;
+    }
+
+    // Header editing rewrites name, region and revision of a ROM
+    if (argv[1][1] == 'n')
+    {
+        if (argc < 4)
+        {
+            wadutil64_help();
+            return EXIT_FAILURE;
+        }
+
+        input_file_name = argv[2];
+        if (!ROM_ResolvePath(input_file_name))
+        {
+            return EXIT_FAILURE;
+        }
+        FILE* input_ROM = fopen(input_file_name.c_str(), "rb");
+        if (!input_ROM)
+        {
+            printf("ERROR: Input file %s not found!\n", input_file_name.c_str());
+            return EXIT_FAILURE;
+        }
+
+        std::vector<byte> rom = ROM_Read(input_ROM, NULL);
+        fclose(input_ROM);
+        if (rom.empty())
+        {
+            return EXIT_FAILURE;
+        }
+
+        for (int i = 3; i < argc; ++i)
+        {
+            if (!ROM_SetHeaderField(rom, argv[i]))
+            {
+                return EXIT_FAILURE;
+            }
+        }
+        ROM_FixCRC(rom);
+
+        output_file_name = output_name(input_file_name, "_header.z64");
+
+        FILE* output_ROM = fopen(output_file_name.c_str(), "wb");
+        if (!output_ROM)
+        {
+            printf("ERROR: Could not write %s!\n", output_file_name.c_str());
+            return EXIT_FAILURE;
+        }
+        fwrite(rom.data(), rom.size(), 1, output_ROM);
+        fclose(output_ROM);
+
+        ROM_PrintHeader(rom);
+        printf("Header written to %s\n", output_file_name.c_str());
+        return EXIT_SUCCESS;
+    }
+
+    // Injection reads a ROM and a WAD and writes a patched ROM
+    if (argv[1][1] == 'j')
+    {
+        byte patch_format;
+        const char* output_extension;
+        if (argc == 4 || (argc == 5 && !strcmp(argv[4], "z64")))
+        {
+            patch_format = PATCH_NONE;
+            output_extension = "_inject.z64";
+        }
+        else if (argc == 5 && !strcmp(argv[4], "ips"))
+        {
+            patch_format = PATCH_IPS;
+            output_extension = "_inject.ips";
+        }
+        else if (argc == 5 && !strcmp(argv[4], "bps"))
+        {
+            patch_format = PATCH_BPS;
+            output_extension = "_inject.bps";
+        }
+        else
+        {
+            wadutil64_help();
+            return EXIT_FAILURE;
+        }
+
+        input_file_name = argv[2];
+        if (!ROM_ResolvePath(input_file_name))
+        {
+            return EXIT_FAILURE;
+        }
+        FILE* input_ROM = fopen(input_file_name.c_str(), "rb");
+        if (!input_ROM)
+        {
+            printf("ERROR: Input file %s not found!\n", input_file_name.c_str());
+            return EXIT_FAILURE;
+        }
+        FILE* input_WAD = fopen(argv[3], "rb");
+        if (!input_WAD)
+        {
+            printf("ERROR: Input file %s not found!\n", argv[3]);
+            fclose(input_ROM);
+            return EXIT_FAILURE;
+        }
+
+        output_file_name = output_name(input_file_name, output_extension);
+        printf("Injection mode enabled!\n");
+
+        FILE* output_ROM = fopen(output_file_name.c_str(), "wb");
+        if (!output_ROM)
+        {
+            printf("ERROR: Could not write %s!\n", output_file_name.c_str());
+            fclose(input_ROM);
+            fclose(input_WAD);
+            return EXIT_FAILURE;
+        }
+
+        bool injected = inject_WAD(input_ROM, input_WAD, output_ROM, patch_format);
+
+        fclose(input_ROM);
+        fclose(input_WAD);
+        fclose(output_ROM);
+
+        if (!injected)
+        {
+            remove(output_file_name.c_str());
+            return EXIT_FAILURE;
+        }
+
+        printf("Injection complete!\n");
+        return EXIT_SUCCESS;
+    }
+
     if (argc != 3)
     {
         wadutil64_help();
@@ -379,40 +1519,40 @@ int main(int argc, char** argv)
     }
 
     // Open input file
-    strncpy(input_file_name, argv[2], 128);
-    FILE* input_file = fopen(input_file_name, "rb");
+    input_file_name = argv[2];
+    if (argv[1][1] == 'e' && !ROM_ResolvePath(input_file_name))
+    {
+        return EXIT_FAILURE;
+    }
+    FILE* input_file = fopen(input_file_name.c_str(), "rb");
     if (!input_file)
     {
-        printf("ERROR: Input file %s not found!\n", input_file_name);
+        printf("ERROR: Input file %s not found!\n", input_file_name.c_str());
         return EXIT_FAILURE;
     }
 
-    // Modify output file name
-    strncpy(output_file_name, input_file_name, 128);
-    output_file_name[strlen(output_file_name) - 4] = 0;
-
     byte program_mode;
     char program_mode_user_input = argv[1][1];
     switch (program_mode_user_input)
     {
     case 'e':
         program_mode = EXTRACT_MODE;
-        strcat(output_file_name, "_extract.WAD");
+        output_file_name = output_name(input_file_name, "_extract.WAD");
         printf("Extraction mode enabled!\n");
         break;
     case 'd':
         program_mode = DECOMPRESS_MODE;
-        strcat(output_file_name, "_decomp.WAD");
+        output_file_name = output_name(input_file_name, "_decomp.WAD");
         printf("Decompression mode enabled!\n");
         break;
     case 'c':
         program_mode = COMPRESS_MODE;
-        strcat(output_file_name, "_comp.WAD");
+        output_file_name = output_name(input_file_name, "_comp.WAD");
         printf("Compression mode enabled!\n");
         break;
     case 'p':
         program_mode = PAD_MODE;
-        strcat(output_file_name, "_pad.WAD");
+        output_file_name = output_name(input_file_name, "_pad.WAD");
         printf("Padding mode enabled!\n");
         break;
     default:
@@ -421,7 +1561,7 @@ int main(int argc, char** argv)
     }
 
     // Create output file
-    FILE* output_file = fopen(output_file_name, "wb");
+    FILE* output_file = fopen(output_file_name.c_str(), "wb");
     if (!output_file)
     {
         printf("ERROR: Could not write decompressed WAD!\n");
@@ -431,7 +1571,13 @@ int main(int argc, char** argv)
     switch (program_mode)
     {
     case EXTRACT_MODE:
-        extract_WAD(input_file, output_file);
+        if (!extract_WAD(input_file, output_file))
+        {
+            fclose(input_file);
+            fclose(output_file);
+            remove(output_file_name.c_str());
+            return EXIT_FAILURE;
+        }
         printf("Extraction complete!\n");
         break;
     case DECOMPRESS_MODE:
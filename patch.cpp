@@ -0,0 +1,168 @@
+/* patch.cpp */
+
+#include "wadutil64_def.h"
+
+/*=========*/
+/* DEFINES */
+/*=========*/
+
+#define IPS_MAX_OFFSET      0xFFFFFF
+#define IPS_MAX_RECORD      0xFFFF
+#define IPS_EOF_OFFSET      0x454F46    // "EOF" would be read as the end marker
+
+#define BPS_SOURCE_READ     0
+#define BPS_TARGET_READ     1
+
+/*
+============================================================================
+
+IPS ROUTINES
+
+============================================================================
+*/
+
+/*
+========================
+=
+= Patch_CreateIPS
+=
+= Writes one record per run of differing bytes. Source and target must be
+= the same size and no larger than 16 MiB.
+=
+========================
+*/
+
+std::vector<byte> Patch_CreateIPS(std::vector<byte>& source, std::vector<byte>& target)
+{
+    std::vector<byte> patch;
+
+    if (source.size() != target.size() || target.size() > IPS_MAX_OFFSET + 1)
+    {
+        printf("ERROR: IPS patches need equally sized ROMs of at most 16 MiB.\n");
+        return patch;
+    }
+
+    const char* header = "PATCH";
+    patch.insert(patch.end(), header, header + 5);
+
+    int size = (int) target.size();
+    int offset = 0;
+    while (offset < size)
+    {
+        if (source[offset] == target[offset])
+        {
+            ++offset;
+            continue;
+        }
+
+        // Back up one byte so the record offset is never mistaken for the footer
+        int start = (offset == IPS_EOF_OFFSET) ? offset - 1 : offset;
+        int end = offset;
+        while (end < size && end - start < IPS_MAX_RECORD && source[end] != target[end])
+        {
+            ++end;
+        }
+
+        int length = end - start;
+        patch.push_back((byte) (start >> 16));
+        patch.push_back((byte) (start >> 8));
+        patch.push_back((byte) start);
+        patch.push_back((byte) (length >> 8));
+        patch.push_back((byte) length);
+        patch.insert(patch.end(), target.begin() + start, target.begin() + end);
+
+        offset = end;
+    }
+
+    const char* footer = "EOF";
+    patch.insert(patch.end(), footer, footer + 3);
+
+    return patch;
+}
+
+/*
+============================================================================
+
+BPS ROUTINES
+
+============================================================================
+*/
+
+static void BPS_WriteNumber(std::vector<byte>& patch, unsigned long long value)
+{
+    while (true)
+    {
+        byte data = value & 0x7F;
+        value >>= 7;
+        if (value == 0)
+        {
+            patch.push_back(0x80 | data);
+            break;
+        }
+        patch.push_back(data);
+        value--;
+    }
+}
+
+static void BPS_WriteCRC(std::vector<byte>& patch, unsigned int crc)
+{
+    patch.push_back((byte) crc);
+    patch.push_back((byte) (crc >> 8));
+    patch.push_back((byte) (crc >> 16));
+    patch.push_back((byte) (crc >> 24));
+}
+
+/*
+========================
+=
+= Patch_CreateBPS
+=
+= Copies unchanged runs from the source and stores changed runs verbatim.
+= Source and target must be the same size.
+=
+========================
+*/
+
+std::vector<byte> Patch_CreateBPS(std::vector<byte>& source, std::vector<byte>& target)
+{
+    std::vector<byte> patch;
+
+    if (source.size() != target.size())
+    {
+        printf("ERROR: BPS patches need equally sized ROMs.\n");
+        return patch;
+    }
+
+    const char* header = "BPS1";
+    patch.insert(patch.end(), header, header + 4);
+    BPS_WriteNumber(patch, source.size());
+    BPS_WriteNumber(patch, target.size());
+    BPS_WriteNumber(patch, 0);  // no metadata
+
+    int size = (int) target.size();
+    int offset = 0;
+    while (offset < size)
+    {
+        bool same = source[offset] == target[offset];
+        int end = offset;
+        while (end < size && (source[end] == target[end]) == same)
+        {
+            ++end;
+        }
+
+        int length = end - offset;
+        BPS_WriteNumber(patch, ((unsigned long long) (length - 1) << 2) | (same ? BPS_SOURCE_READ : BPS_TARGET_READ));
+        if (!same)
+        {
+            patch.insert(patch.end(), target.begin() + offset, target.begin() + end);
+        }
+
+        offset = end;
+    }
+
+    BPS_WriteCRC(patch, crc32(source.data(), (int) source.size()));
+    BPS_WriteCRC(patch, crc32(target.data(), (int) target.size()));
+    BPS_WriteCRC(patch, crc32(patch.data(), (int) patch.size()));
+
+    return patch;
+}
@@ -0,0 +1,585 @@
+/* rom.cpp */
+
+#include <algorithm>
+#include <filesystem>
+#include <string>
+#include "wadutil64_def.h"
+
+/*=========*/
+/* DEFINES */
+/*=========*/
+
+#define ROM_MAGIC_Z64       0x80371240
+#define ROM_MAGIC_V64       0x37804012
+#define ROM_MAGIC_N64       0x40123780
+
+#define ROM_ENTRY_OFFSET    0x08
+#define ROM_CRC1_OFFSET     0x10
+#define ROM_CRC2_OFFSET     0x14
+#define ROM_HEADER_SIZE     0x40
+#define ROM_NAME_OFFSET     0x20
+#define ROM_NAME_LENGTH     20
+#define ROM_CODE_OFFSET     0x3B
+#define ROM_VERSION_OFFSET  0x3F
+#define ROM_CRC_START       0x1000
+#define ROM_CRC_LENGTH      0x100000
+#define CIC_6102_SEED       0xF8CA4DDC
+
+#define ROL(i, b) (((i) << (b)) | ((i) >> ((32 - (b)) & 31)))
+
+/*
+============================================================================
+
+HELPER ROUTINES
+
+============================================================================
+*/
+
+static unsigned int ReadBE32(std::vector<byte>& rom, int offset)
+{
+    return ((unsigned int) rom[offset] << 24) | ((unsigned int) rom[offset + 1] << 16)
+        | ((unsigned int) rom[offset + 2] << 8) | (unsigned int) rom[offset + 3];
+}
+
+static void WriteBE32(std::vector<byte>& rom, int offset, unsigned int value)
+{
+    rom[offset] = (byte) (value >> 24);
+    rom[offset + 1] = (byte) (value >> 16);
+    rom[offset + 2] = (byte) (value >> 8);
+    rom[offset + 3] = (byte) value;
+}
+
+static int ReadLE32(std::vector<byte>& rom, int offset)
+{
+    return (int) ((unsigned int) rom[offset] | ((unsigned int) rom[offset + 1] << 8)
+        | ((unsigned int) rom[offset + 2] << 16) | ((unsigned int) rom[offset + 3] << 24));
+}
+
+/*
+============================================================================
+
+ROM ROUTINES
+
+============================================================================
+*/
+
+/*
+========================
+=
+= ROM_SwapFormat
+=
+= Converts between .z64 order and the given byte order. Both swaps are their
+= own inverse, so this works in either direction.
+=
+========================
+*/
+
+void ROM_SwapFormat(std::vector<byte>& rom, byte format)
+{
+    size_t size = rom.size();
+
+    if (format == ROM_FORMAT_V64)
+    {
+        for (size_t i = 0; i + 1 < size; i += 2)
+        {
+            std::swap(rom[i], rom[i + 1]);
+        }
+    }
+    else if (format == ROM_FORMAT_N64)
+    {
+        for (size_t i = 0; i + 3 < size; i += 4)
+        {
+            std::swap(rom[i], rom[i + 3]);
+            std::swap(rom[i + 1], rom[i + 2]);
+        }
+    }
+}
+
+/*
+========================
+=
+= ROM_FormatFromMagic
+=
+= Returns the byte order of a ROM from its first word, or -1 if unknown.
+=
+========================
+*/
+
+static int ROM_FormatFromMagic(unsigned int magic)
+{
+    switch (magic)
+    {
+    case ROM_MAGIC_Z64:
+        return ROM_FORMAT_Z64;
+    case ROM_MAGIC_V64:
+        return ROM_FORMAT_V64;
+    case ROM_MAGIC_N64:
+        return ROM_FORMAT_N64;
+    default:
+        return -1;
+    }
+}
+
+/*
+========================
+=
+= ROM_ReadFormat
+=
+= Returns the byte order of a ROM file, or -1 if it does not start like one.
+= The file position is reset to the start.
+=
+========================
+*/
+
+int ROM_ReadFormat(FILE *file)
+{
+    std::vector<byte> magic(4);
+    fseek(file, 0, SEEK_SET);
+    bool read = fread(magic.data(), 4, 1, file) == 1;
+    fseek(file, 0, SEEK_SET);
+
+    return read ? ROM_FormatFromMagic(ReadBE32(magic, 0)) : -1;
+}
+
+/*
+========================
+=
+= ROM_Read
+=
+= Loads the whole ROM image into memory. Returns an empty image on failure.
+= Byte-swapped (.v64) and little-endian (.n64) dumps are converted to .z64
+= order so the rest of the code only has to deal with one layout. The
+= original byte order is stored in *format if it is not NULL.
+=
+========================
+*/
+
+std::vector<byte> ROM_Read(FILE *file, byte *format)
+{
+    fseek(file, 0, SEEK_END);
+    long size = ftell(file);
+    fseek(file, 0, SEEK_SET);
+
+    if (size < ROM_CRC_START + ROM_CRC_LENGTH)
+    {
+        printf("ERROR: File is too small to be an N64 ROM.\n");
+        return std::vector<byte>();
+    }
+
+    std::vector<byte> rom(size);
+    if (fread(rom.data(), size, 1, file) != 1)
+    {
+        printf("ERROR: Could not read ROM.\n");
+        return std::vector<byte>();
+    }
+
+    unsigned int magic = ReadBE32(rom, 0);
+    int detected_format = ROM_FormatFromMagic(magic);
+    byte rom_format = (detected_format < 0) ? ROM_FORMAT_Z64 : (byte) detected_format;
+    if (rom_format == ROM_FORMAT_V64)
+    {
+        printf("Byte-swapped (.v64) ROM detected, converting to .z64 order\n");
+    }
+    else if (rom_format == ROM_FORMAT_N64)
+    {
+        printf("Little-endian (.n64) ROM detected, converting to .z64 order\n");
+    }
+    else if (detected_format < 0)
+    {
+        printf("WARNING: Unknown ROM header %08X, assuming .z64 order\n", magic);
+    }
+
+    ROM_SwapFormat(rom, rom_format);
+    if (format)
+    {
+        *format = rom_format;
+    }
+
+    return rom;
+}
+
+/*
+========================
+=
+= ROM_FindIWAD
+=
+= Scans the ROM for an IWAD header whose lump directory fits inside the ROM.
+= Returns the offset of the IWAD or -1 and stores its total size in *size.
+=
+========================
+*/
+
+int ROM_FindIWAD(std::vector<byte>& rom, int *size)
+{
+    int rom_size = (int) rom.size();
+
+    for (int offset = ROM_CRC_START; offset + 12 <= rom_size; offset += 4)
+    {
+        if (memcmp(&rom[offset], "IWAD", 4))
+        {
+            continue;
+        }
+
+        int numlumps = ReadLE32(rom, offset + 4);
+        int infotableofs = ReadLE32(rom, offset + 8);
+        if (numlumps <= 0 || numlumps > 0x10000 || infotableofs < 12)
+        {
+            continue;
+        }
+
+        long wad_size = (long) infotableofs + (long) numlumps * 16;
+        if (offset + wad_size > rom_size)
+        {
+            continue;
+        }
+
+        *size = (int) wad_size;
+        return offset;
+    }
+
+    return -1;
+}
+
+/*
+========================
+=
+= ROM_FindSignature
+=
+= Returns the first word aligned offset of a four character magic or -1.
+=
+========================
+*/
+
+int ROM_FindSignature(std::vector<byte>& rom, const char *magic)
+{
+    int rom_size = (int) rom.size();
+
+    for (int offset = ROM_CRC_START; offset + 4 <= rom_size; offset += 4)
+    {
+        if (!memcmp(&rom[offset], magic, 4))
+        {
+            return offset;
+        }
+    }
+
+    return -1;
+}
+
+/*
+========================
+=
+= ROM_CalculateCRC
+=
+= CIC-6102 checksum over the first megabyte after the boot code.
+=
+========================
+*/
+
+void ROM_CalculateCRC(std::vector<byte>& rom, unsigned int *crc1, unsigned int *crc2)
+{
+    unsigned int t1, t2, t3, t4, t5, t6;
+    t1 = t2 = t3 = t4 = t5 = t6 = CIC_6102_SEED;
+
+    for (int i = ROM_CRC_START; i < ROM_CRC_START + ROM_CRC_LENGTH; i += 4)
+    {
+        unsigned int d = ReadBE32(rom, i);
+        if ((t6 + d) < t6)
+        {
+            t4++;
+        }
+
+        t6 += d;
+        t3 ^= d;
+
+        unsigned int r = ROL(d, d & 0x1F);
+        t5 += r;
+
+        if (t2 > d)
+        {
+            t2 ^= r;
+        }
+        else
+        {
+            t2 ^= t6 ^ d;
+        }
+
+        t1 += t5 ^ d;
+    }
+
+    *crc1 = t6 ^ t4 ^ t3;
+    *crc2 = t5 ^ t2 ^ t1;
+}
+
+/*
+========================
+=
+= ROM_FixCRC
+=
+========================
+*/
+
+void ROM_FixCRC(std::vector<byte>& rom)
+{
+    unsigned int crc1, crc2;
+    ROM_CalculateCRC(rom, &crc1, &crc2);
+
+    WriteBE32(rom, ROM_CRC1_OFFSET, crc1);
+    WriteBE32(rom, ROM_CRC2_OFFSET, crc2);
+}
+
+/*
+========================
+=
+= ROM_RegionName
+=
+========================
+*/
+
+static const char* ROM_RegionName(byte code)
+{
+    switch (code)
+    {
+    case 'E':
+        return "USA";
+    case 'J':
+        return "Japan";
+    case 'P':
+        return "Europe";
+    case 'D':
+        return "Germany";
+    case 'F':
+        return "France";
+    default:
+        return "Unknown";
+    }
+}
+
+/*
+========================
+=
+= ROM_PrintHeader
+=
+= Prints the cartridge header fields and checks the stored CRC.
+= Returns false if the stored CRC does not match.
+=
+========================
+*/
+
+bool ROM_PrintHeader(std::vector<byte>& rom)
+{
+    char name[ROM_NAME_LENGTH + 1];
+    memcpy(name, &rom[ROM_NAME_OFFSET], ROM_NAME_LENGTH);
+    name[ROM_NAME_LENGTH] = 0;
+    for (int i = ROM_NAME_LENGTH - 1; i >= 0 && (name[i] == ' ' || name[i] == 0); --i)
+    {
+        name[i] = 0;
+    }
+
+    byte region = rom[ROM_CODE_OFFSET + 3];
+
+    printf("Name: %s\n", name);
+    printf("Game code: %.4s\n", (char*) &rom[ROM_CODE_OFFSET]);
+    printf("Region: %s (%c)\n", ROM_RegionName(region), isprint(region) ? region : '?');
+    printf("Revision: %d\n", rom[ROM_VERSION_OFFSET]);
+    printf("Entry point: %08X\n", ReadBE32(rom, ROM_ENTRY_OFFSET));
+
+    unsigned int crc1, crc2;
+    ROM_CalculateCRC(rom, &crc1, &crc2);
+    unsigned int stored_crc1 = ReadBE32(rom, ROM_CRC1_OFFSET);
+    unsigned int stored_crc2 = ReadBE32(rom, ROM_CRC2_OFFSET);
+    bool crc_ok = (crc1 == stored_crc1 && crc2 == stored_crc2);
+
+    printf("CRC: %08X %08X (%s)\n", stored_crc1, stored_crc2, crc_ok ? "OK" : "BAD");
+    if (!crc_ok)
+    {
+        printf("Expected CRC: %08X %08X\n", crc1, crc2);
+    }
+
+    int wad_size;
+    int wad_offset = ROM_FindIWAD(rom, &wad_size);
+    if (wad_offset >= 0)
+    {
+        printf("IWAD: %X, size %d\n", wad_offset, wad_size);
+    }
+    else
+    {
+        printf("IWAD: not found\n");
+    }
+
+    return crc_ok;
+}
+
+/*
+========================
+=
+= ROM_SetHeaderField
+=
+= Sets name, region or revision from a field=value pair. The CRC does not
+= cover the header, so it stays valid.
+=
+========================
+*/
+
+bool ROM_SetHeaderField(std::vector<byte>& rom, const char *assignment)
+{
+    const char* value = strchr(assignment, '=');
+    if (!value)
+    {
+        printf("ERROR: Expected field=value, got %s\n", assignment);
+        return false;
+    }
+    size_t field_length = value - assignment;
+    value++;
+
+    if (field_length == 4 && !strncmp(assignment, "name", 4))
+    {
+        if (strlen(value) > ROM_NAME_LENGTH)
+        {
+            printf("ERROR: ROM name can be at most %d characters\n", ROM_NAME_LENGTH);
+            return false;
+        }
+        memset(&rom[ROM_NAME_OFFSET], ' ', ROM_NAME_LENGTH);
+        memcpy(&rom[ROM_NAME_OFFSET], value, strlen(value));
+    }
+    else if (field_length == 6 && !strncmp(assignment, "region", 6))
+    {
+        if (strlen(value) != 1 || !isupper((unsigned char) value[0]))
+        {
+            printf("ERROR: Region must be a single uppercase letter such as E, J or P\n");
+            return false;
+        }
+        rom[ROM_CODE_OFFSET + 3] = value[0];
+    }
+    else if (field_length == 8 && !strncmp(assignment, "revision", 8))
+    {
+        char* end;
+        long revision = strtol(value, &end, 10);
+        if (*value == 0 || *end != 0 || revision < 0 || revision > 255)
+        {
+            printf("ERROR: Revision must be a number from 0 to 255\n");
+            return false;
+        }
+        rom[ROM_VERSION_OFFSET] = (byte) revision;
+    }
+    else
+    {
+        printf("ERROR: Unknown header field %.*s, expected name, region or revision\n", (int) field_length, assignment);
+        return false;
+    }
+
+    return true;
+}
+
+/*
+========================
+=
+= ROM_IsDoom64
+=
+= Checks the header of a ROM file in any byte order for the Doom 64 game code.
+=
+========================
+*/
+
+static bool ROM_IsDoom64(const std::filesystem::path& path)
+{
+    FILE* file = fopen(path.string().c_str(), "rb");
+    if (!file)
+    {
+        return false;
+    }
+
+    std::vector<byte> header(ROM_HEADER_SIZE);
+    bool read = fread(header.data(), ROM_HEADER_SIZE, 1, file) == 1;
+    fclose(file);
+    if (!read)
+    {
+        return false;
+    }
+
+    int format = ROM_FormatFromMagic(ReadBE32(header, 0));
+    if (format < 0)
+    {
+        return false;
+    }
+    ROM_SwapFormat(header, (byte) format);
+
+    // Game code is NDMx, the last letter being the region
+    return header[ROM_CODE_OFFSET + 1] == 'D' && header[ROM_CODE_OFFSET + 2] == 'M';
+}
+
+/*
+========================
+=
+= ROM_ResolvePath
+=
+= Flashcart SD cards keep the ROM next to saves and other games. If path is a
+= directory, it is searched recursively and replaced with the path of the
+= first Doom 64 ROM found. Returns false if the directory has none.
+=
+========================
+*/
+
+bool ROM_ResolvePath(std::string& path)
+{
+    std::error_code error;
+    if (!std::filesystem::is_directory(path, error))
+    {
+        return true;
+    }
+
+    // Walk one folder at a time, so an unreadable folder like System Volume
+    // Information only loses its own entries instead of ending the search
+    std::vector<std::filesystem::path> roms;
+    std::vector<std::filesystem::path> folders = { path };
+    auto options = std::filesystem::directory_options::skip_permission_denied;
+    while (!folders.empty())
+    {
+        std::filesystem::path folder = folders.back();
+        folders.pop_back();
+
+        for (auto it = std::filesystem::directory_iterator(folder, options, error);
+            it != std::filesystem::directory_iterator(); it.increment(error))
+        {
+            // Like recursive_directory_iterator, don't follow directory symlinks
+            if (it->is_directory(error) && !it->is_symlink(error))
+            {
+                folders.push_back(it->path());
+                continue;
+            }
+            if (!it->is_regular_file(error))
+            {
+                continue;
+            }
+
+            std::string extension = it->path().extension().string();
+            std::transform(extension.begin(), extension.end(), extension.begin(), ::tolower);
+            if (extension != ".z64" && extension != ".v64" && extension != ".n64")
+            {
+                continue;
+            }
+
+            if (ROM_IsDoom64(it->path()))
+            {
+                roms.push_back(it->path());
+            }
+        }
+        error.clear();
+    }
+
+    if (roms.empty())
+    {
+        printf("ERROR: No Doom 64 ROM found in %s!\n", path.c_str());
+        return false;
+    }
+
+    std::sort(roms.begin(), roms.end());
+    path = roms[0].string();
+
+    printf("Using ROM: %s\n", path.c_str());
+    for (size_t i = 1; i < roms.size(); ++i)
+    {
+        printf("WARNING: Ignoring other Doom 64 ROM %s\n", roms[i].string().c_str());
+    }
+
+    return true;
+}
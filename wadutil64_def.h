@@ -2,10 +2,29 @@
 #include <iostream>
 #include <cstdio>
 #include <cstring>
+#include <cctype>
 #include <vector>
+#include <string>
 
 typedef unsigned char byte;
 
+#define ROM_FORMAT_Z64  0   /* big-endian */
+#define ROM_FORMAT_V64  1   /* byte-swapped */
+#define ROM_FORMAT_N64  2   /* little-endian */
+
 void DecodeD64(unsigned char *input, unsigned char *output);
 void DecodeJaguar(unsigned char *input, unsigned char *output);
-std::vector<byte> Deflate_Encode(byte *input, int size);
\ No newline at end of file
+std::vector<byte> Deflate_Encode(byte *input, int size);
+int ROM_ReadFormat(FILE *file);
+std::vector<byte> ROM_Read(FILE *file, byte *format);
+void ROM_SwapFormat(std::vector<byte>& rom, byte format);
+int ROM_FindIWAD(std::vector<byte>& rom, int *size);
+int ROM_FindSignature(std::vector<byte>& rom, const char *magic);
+void ROM_CalculateCRC(std::vector<byte>& rom, unsigned int *crc1, unsigned int *crc2);
+void ROM_FixCRC(std::vector<byte>& rom);
+bool ROM_PrintHeader(std::vector<byte>& rom);
+bool ROM_SetHeaderField(std::vector<byte>& rom, const char *assignment);
+bool ROM_ResolvePath(std::string& path);
+std::vector<byte> Patch_CreateIPS(std::vector<byte>& source, std::vector<byte>& target);
+std::vector<byte> Patch_CreateBPS(std::vector<byte>& source, std::vector<byte>& target);
+unsigned int crc32(const byte* data, int size);
\ No newline at end of file